@@ -0,0 +1,503 @@
+import io
+import os
+import tempfile
+import threading
+import unittest
+from array import array
+from concurrent.futures import wait
+from unittest import mock
+
+import x8Dquanta
+from x8Dquanta import (
+    LAW,
+    DuplicateTensorError,
+    TensorsBuilder,
+    golden,
+    iter_chunks,
+    load_file,
+    load_from_reader,
+    save_file,
+    save_to_writer,
+    snapshot,
+    x8DSubByte,
+)
+
+
+class TempDirTestCase(unittest.TestCase):
+    """Gives each test a private directory and a target path inside it."""
+
+    def setUp(self):
+        self._tmpdir = tempfile.TemporaryDirectory()
+        self.dirname = self._tmpdir.name
+        self.filename = os.path.join(self.dirname, "test.bin")
+
+    def tearDown(self):
+        self._tmpdir.cleanup()
+
+
+class LosslessTestCase(TempDirTestCase):
+    def test_lossless_roundtrip_every_dtype(self):
+        # One array per native element type: signed/unsigned ints and floats
+        for typecode in "bBhHiIlLqQfd":
+            with self.subTest(typecode=typecode):
+                data = array(typecode, range(0, 100, 7))
+                save_file({"test": data}, self.filename, lossless=True)
+                self.assertEqual(load_file(self.filename), data.tobytes())
+
+    def test_lossless_roundtrip_multiple_tensors(self):
+        tensors = {"a": bytes(range(256)), "b": bytearray(b"\x00\xff"), "c": [1, 2, 3]}
+        save_file(tensors, self.filename, lossless=True)
+        self.assertEqual(load_file(self.filename), bytes(range(256)) + b"\x00\xff\x01\x02\x03")
+
+    def test_default_mode_reduces(self):
+        save_file({"test": bytes(range(256))}, self.filename)
+        self.assertEqual(load_file(self.filename), bytes([sum(range(256)) % 256]))
+
+
+class PreviousFileTestCase(TempDirTestCase):
+    """Starts each test with a good checkpoint already at the target path."""
+
+    def setUp(self):
+        super().setUp()
+        save_file({"good": bytes(range(256))}, self.filename, lossless=True)
+
+    def assertPreviousFileIntact(self):
+        self.assertEqual(load_file(self.filename), bytes(range(256)))
+        self.assertEqual(os.listdir(self.dirname), ["test.bin"])
+
+
+class AtomicSaveTestCase(PreviousFileTestCase):
+    def test_failed_save_keeps_previous_file(self):
+        for fsync in (False, True):
+            with self.subTest(fsync=fsync):
+                with mock.patch("os.fsync") as fsync_mock:
+                    with self.assertRaises(TypeError):
+                        save_file({"a": b"\x01", "b": "str"}, self.filename, fsync=fsync)
+                fsync_mock.assert_not_called()
+                self.assertPreviousFileIntact()
+
+    def test_builder_aborts_on_exception(self):
+        with self.assertRaises(RuntimeError):
+            with TensorsBuilder(self.filename) as builder:
+                builder.add_tensor("a", b"\x01")
+                raise RuntimeError("producer failed")
+        self.assertPreviousFileIntact()
+
+    def test_explicit_abort(self):
+        builder = TensorsBuilder(self.filename)
+        builder.add_tensor("a", b"\x01")
+        builder.abort()
+        self.assertPreviousFileIntact()
+
+
+@unittest.skipIf(os.name == "nt", "POSIX permissions and symlinks")
+class ReplaceTargetTestCase(TempDirTestCase):
+    def test_existing_mode_preserved(self):
+        save_file({"a": b"\x01"}, self.filename)
+        os.chmod(self.filename, 0o600)
+        save_file({"a": b"\x02"}, self.filename)
+        self.assertEqual(os.stat(self.filename).st_mode & 0o777, 0o600)
+
+    def test_new_file_mode_matches_open(self):
+        reference = os.path.join(self.dirname, "reference.bin")
+        open(reference, "wb").close()
+        save_file({"a": b"\x01"}, self.filename)
+        self.assertEqual(os.stat(self.filename).st_mode & 0o777, os.stat(reference).st_mode & 0o777)
+
+    def test_writes_through_symlink(self):
+        target = os.path.join(self.dirname, "ckpt5.bin")
+        save_file({"a": b"\x01"}, target)
+        link = os.path.join(self.dirname, "latest.bin")
+        os.symlink("ckpt5.bin", link)
+        save_file({"a": b"\x02"}, link)
+        self.assertTrue(os.path.islink(link))
+        self.assertEqual(load_file(target), b"\x02")
+        self.assertEqual(sorted(os.listdir(self.dirname)), ["ckpt5.bin", "latest.bin"])
+
+
+class VerifyTestCase(PreviousFileTestCase):
+    def test_mismatch_keeps_previous_file(self):
+        verify_file = x8Dquanta._verify_file
+
+        def corrupt_then_verify(filename, written):
+            # Flip the stored byte after the builder recorded its checksum
+            with open(filename, "r+b") as f:
+                f.write(b"\xff")
+            return verify_file(filename, written)
+
+        with mock.patch("x8Dquanta._verify_file", corrupt_then_verify):
+            with self.assertRaises(OSError):
+                save_file({"a": b"\x01"}, self.filename, verify=True)
+        self.assertPreviousFileIntact()
+
+    def test_failed_input_leaves_no_temp_file(self):
+        with self.assertRaises(TypeError):
+            save_file({"a": b"\x01", "b": "str"}, self.filename, verify=True)
+        self.assertPreviousFileIntact()
+
+    def test_report_replaces_target(self):
+        report = save_file({"a": b"\x01\x02"}, self.filename, verify=True)
+        self.assertEqual(report["size"], 1)
+        self.assertEqual(load_file(self.filename), b"\x03")
+        self.assertEqual(os.listdir(self.dirname), ["test.bin"])
+
+
+class FsyncTestCase(TempDirTestCase):
+    def test_not_synced_by_default(self):
+        with mock.patch("os.fsync") as fsync_mock:
+            save_file({"a": b"\x01"}, self.filename)
+        fsync_mock.assert_not_called()
+
+    def test_file_and_directory_synced(self):
+        synced = []
+
+        def recording_fsync(fd):
+            synced.append(os.fstat(fd))
+
+        with mock.patch("os.fsync", recording_fsync):
+            save_file({"a": b"\x01"}, self.filename, fsync=True)
+        # The temp file (renamed to the target) and then its directory
+        expected = [os.stat(self.filename)] if os.name == "nt" else [os.stat(self.filename), os.stat(self.dirname)]
+        self.assertEqual([(st.st_dev, st.st_ino) for st in synced],
+                         [(st.st_dev, st.st_ino) for st in expected])
+
+
+class LockTestCase(TempDirTestCase):
+    @unittest.skipIf(os.name == "nt", "flock is POSIX-only")
+    def test_sidecar_locked_while_builder_open(self):
+        import fcntl
+
+        with TensorsBuilder(self.filename, lock=True) as builder:
+            builder.add_tensor("a", b"\x01")
+            with open(self.filename + ".lock", "rb") as other:
+                with self.assertRaises(BlockingIOError):
+                    fcntl.flock(other.fileno(), fcntl.LOCK_EX | fcntl.LOCK_NB)
+        self.assertEqual(os.listdir(self.dirname), ["test.bin"])
+        self.assertEqual(load_file(self.filename, lock=True), b"\x01")
+
+    def test_blocked_reader_sees_new_file(self):
+        save_file({"old": b"\x01"}, self.filename, lossless=True)
+        result = []
+        builder = TensorsBuilder(self.filename, lock=True, lossless=True)
+        builder.add_tensor("new", b"\x02")
+        reader = threading.Thread(target=lambda: result.append(load_file(self.filename, lock=True)))
+        reader.start()
+        reader.join(0.2)
+        self.assertTrue(reader.is_alive())
+        builder.finish()
+        reader.join(10)
+        self.assertEqual(result, [b"\x02"])
+
+    def test_failed_locked_save_leaves_nothing(self):
+        with self.assertRaises(TypeError):
+            save_file({"a": b"\x01", "b": "str"}, self.filename, lock=True)
+        self.assertEqual(os.listdir(self.dirname), [])
+
+    def test_lock_failure_closes_handle(self):
+        opened = []
+        fdopen = os.fdopen
+
+        def tracking_fdopen(*args, **kwargs):
+            opened.append(fdopen(*args, **kwargs))
+            return opened[-1]
+
+        with mock.patch("os.fdopen", tracking_fdopen), \
+                mock.patch("x8Dquanta._lock_file", side_effect=OSError("lock timed out")):
+            with self.assertRaises(OSError):
+                TensorsBuilder(self.filename, lock=True)
+        self.assertEqual(len(opened), 1)
+        self.assertTrue(opened[0].closed)
+        # No target and no temp file; an unlocked sidecar may belong to
+        # another holder, so it is never unlinked without the lock
+        self.assertEqual(os.listdir(self.dirname), ["test.bin.lock"])
+
+
+class DuplicateNameTestCase(unittest.TestCase):
+    def test_duplicate_raises_by_default(self):
+        builder = TensorsBuilder(io.BytesIO())
+        builder.add_tensor("a", b"\x01")
+        with self.assertRaises(DuplicateTensorError):
+            builder.add_tensor("a", b"\x02")
+        with self.assertRaises(DuplicateTensorError):
+            builder.add_tensor_chunks("a", [b"\x02"])
+
+    def test_first_wins(self):
+        out = io.BytesIO()
+        with TensorsBuilder(out, duplicates="first", lossless=True) as builder:
+            builder.add_tensor("a", b"\x01")
+            builder.add_tensor("a", b"\x02")
+            builder.add_tensor_chunks("a", [b"\x03"])
+        self.assertEqual(out.getvalue(), b"\x01")
+        self.assertEqual([name for name, _, _ in builder.written], ["a"])
+
+    def test_unknown_policy(self):
+        with self.assertRaises(ValueError):
+            TensorsBuilder(io.BytesIO(), duplicates="last")
+
+
+class PartialTensorTestCase(PreviousFileTestCase):
+    def test_retry_after_type_error(self):
+        out = io.BytesIO()
+        builder = TensorsBuilder(out, lossless=True)
+        with self.assertRaises(TypeError):
+            builder.add_tensor("a", object())
+        builder.add_tensor("a", b"\x01")
+        builder.finish()
+        self.assertEqual(out.getvalue(), b"\x01")
+        self.assertEqual([name for name, _, _ in builder.written], ["a"])
+
+    def test_failed_chunks_make_builder_unusable(self):
+        def chunks():
+            yield b"\x01"
+            raise RuntimeError("source failed")
+
+        builder = TensorsBuilder(self.filename, lossless=True)
+        builder.add_tensor("a", b"\x02")
+        with self.assertRaises(RuntimeError):
+            builder.add_tensor_chunks("b", chunks())
+        self.assertEqual([name for name, _, _ in builder.written], ["a"])
+        with self.assertRaises(ValueError):
+            builder.add_tensor("b", b"\x03")
+        with self.assertRaises(ValueError):
+            builder.finish()
+        self.assertPreviousFileIntact()
+
+
+class EmptyTestCase(TempDirTestCase):
+    def test_empty_tensor_set(self):
+        for lossless in (False, True):
+            with self.subTest(lossless=lossless):
+                report = save_file({}, self.filename, lossless=lossless, verify=True)
+                self.assertEqual(report, {"size": 0, "tensors": {}})
+                self.assertEqual(load_file(self.filename), b"")
+
+    def test_empty_tensor(self):
+        report = save_file({"empty": b"", "a": b"\x01"}, self.filename, verify=True)
+        self.assertEqual(report["size"], 1)
+        self.assertEqual(list(report["tensors"]), ["empty", "a"])
+        self.assertEqual(load_file(self.filename), b"\x01")
+
+
+class ShortWriter(io.RawIOBase):
+    """Raw stream that accepts at most three bytes per write() call."""
+
+    def __init__(self):
+        self.data = bytearray()
+
+    def writable(self):
+        return True
+
+    def write(self, b):
+        chunk = bytes(b[:3])
+        self.data += chunk
+        return len(chunk)
+
+
+class WriterTestCase(TempDirTestCase):
+    def test_builder_streams_to_writer(self):
+        out = io.BytesIO()
+        with TensorsBuilder(out, lossless=True) as builder:
+            builder.add_tensor("a", b"\x01\x02")
+            builder.add_tensor("b", [3])
+        self.assertFalse(out.closed)
+        self.assertEqual(out.getvalue(), b"\x01\x02\x03")
+
+    def test_builder_completes_short_writes(self):
+        out = ShortWriter()
+        with TensorsBuilder(out, lossless=True) as builder:
+            builder.add_tensor("a", bytes(range(10)))
+            builder.add_tensor_chunks("b", [b"\x0a\x0b\x0c\x0d", b"\x0e"])
+        self.assertEqual(bytes(out.data), bytes(range(15)))
+
+    def test_none_from_plain_sink_means_fully_written(self):
+        class PlainSink:
+            def __init__(self):
+                self.data = bytearray()
+
+            def write(self, b):
+                self.data += b
+
+            def flush(self):
+                pass
+
+        out = PlainSink()
+        save_to_writer({"a": b"\x01\x02"}, out, lossless=True)
+        self.assertEqual(bytes(out.data), b"\x01\x02")
+
+    def test_none_from_raw_stream_would_block(self):
+        class WouldBlock(io.RawIOBase):
+            def writable(self):
+                return True
+
+            def write(self, b):
+                return None
+
+        with self.assertRaises(BlockingIOError):
+            save_to_writer({"a": b"\x01"}, WouldBlock(), lossless=True)
+
+    def test_add_after_finish_raises(self):
+        out = io.BytesIO()
+        builder = TensorsBuilder(out, lossless=True)
+        builder.add_tensor("a", b"\x01")
+        builder.finish()
+        with self.assertRaises(ValueError):
+            builder.add_tensor("b", b"\x02")
+        with self.assertRaises(ValueError):
+            builder.add_tensor_chunks("c", [b"\x03"])
+        self.assertEqual(out.getvalue(), b"\x01")
+
+    def test_chunks_match_whole_tensor(self):
+        data = bytes(range(256)) * 3
+        for lossless in (False, True):
+            with self.subTest(lossless=lossless):
+                whole, chunked = io.BytesIO(), io.BytesIO()
+                with TensorsBuilder(whole, lossless=lossless) as builder:
+                    builder.add_tensor("a", data)
+                with TensorsBuilder(chunked, lossless=lossless) as builder:
+                    builder.add_tensor_chunks("a", (data[i:i + 5] for i in range(0, len(data), 5)))
+                self.assertEqual(chunked.getvalue(), whole.getvalue())
+
+    def test_save_to_writer_matches_save_file(self):
+        tensors = {"a": bytes(range(256)), "b": [1, 2]}
+        for lossless in (False, True):
+            with self.subTest(lossless=lossless):
+                out = io.BytesIO()
+                save_to_writer(tensors, out, lossless=lossless)
+                save_file(tensors, self.filename, lossless=lossless)
+                self.assertEqual(out.getvalue(), load_file(self.filename))
+
+    def test_save_to_writer_completes_short_writes(self):
+        out = ShortWriter()
+        save_to_writer({"a": bytes(range(10))}, out, lossless=True)
+        self.assertEqual(bytes(out.data), bytes(range(10)))
+
+    def test_load_from_reader(self):
+        out = io.BytesIO()
+        save_to_writer({"a": bytes(range(256))}, out, lossless=True)
+        out.seek(0)
+        self.assertEqual(load_from_reader(out), bytes(range(256)))
+
+
+class SnapshotTestCase(TempDirTestCase):
+    def test_data_pinned_at_call_time(self):
+        buf = bytearray(b"\x01\x02")
+        release = threading.Event()
+
+        def delayed_save_file(*args, **kwargs):
+            # Hold the worker until the caller has mutated its buffer
+            release.wait(10)
+            return save_file(*args, **kwargs)
+
+        with mock.patch("x8Dquanta.save_file", delayed_save_file):
+            future = snapshot({"a": buf}, self.filename, lossless=True)
+            buf[0] = 0xFF
+            release.set()
+            future.result(timeout=10)
+        self.assertEqual(load_file(self.filename), b"\x01\x02")
+
+    def test_same_path_written_in_call_order(self):
+        release = threading.Event()
+
+        def slow_old_save_file(tensors, *args, **kwargs):
+            # The older snapshot stalls until the newer one had its chance
+            if "old" in tensors:
+                release.wait(10)
+            return save_file(tensors, *args, **kwargs)
+
+        with mock.patch("x8Dquanta.save_file", slow_old_save_file):
+            older = snapshot({"old": b"\x01"}, self.filename, lossless=True)
+            newer = snapshot({"new": b"\x02"}, self.filename, lossless=True)
+            wait([newer], timeout=0.5)
+            release.set()
+            older.result(timeout=10)
+            newer.result(timeout=10)
+        self.assertEqual(load_file(self.filename), b"\x02")
+
+    def test_cancelled_middle_snapshot_keeps_order(self):
+        release = threading.Event()
+
+        def slow_old_save_file(tensors, *args, **kwargs):
+            if "old" in tensors:
+                release.wait(10)
+            return save_file(tensors, *args, **kwargs)
+
+        with mock.patch("x8Dquanta.save_file", slow_old_save_file):
+            older = snapshot({"old": b"\x01"}, self.filename, lossless=True)
+            middle = snapshot({"middle": b"\x03"}, self.filename, lossless=True)
+            self.assertTrue(middle.cancel())
+            newer = snapshot({"new": b"\x02"}, self.filename, lossless=True)
+            wait([newer], timeout=0.5)
+            release.set()
+            older.result(timeout=10)
+            newer.result(timeout=10)
+        self.assertEqual(load_file(self.filename), b"\x02")
+
+    def test_callback_fires(self):
+        done = threading.Event()
+        seen = []
+
+        def callback(future):
+            seen.append(future.result())
+            done.set()
+
+        future = snapshot({"a": b"\x01"}, self.filename, callback=callback, verify=True)
+        self.assertTrue(done.wait(10))
+        self.assertEqual(seen, [future.result()])
+        self.assertEqual(seen[0]["size"], 1)
+
+    def test_worker_error_surfaces_through_future(self):
+        future = snapshot({"a": b"\x01"}, os.path.join(self.dirname, "missing", "test.bin"))
+        self.assertIsInstance(future.exception(timeout=10), FileNotFoundError)
+
+    def test_cancel_before_start_writes_nothing(self):
+        # Capture the worker instead of starting it, then run it after cancel()
+        with mock.patch("x8Dquanta.threading.Thread") as thread_cls:
+            future = snapshot({"a": b"\x01"}, self.filename)
+        run = thread_cls.call_args.kwargs["target"]
+        self.assertTrue(future.cancel())
+        run()
+        self.assertTrue(future.cancelled())
+        self.assertFalse(os.path.exists(self.filename))
+
+
+class TransformTestCase(unittest.TestCase):
+    def test_tables_match_arithmetic(self):
+        data = bytes(range(256))
+        points = x8DSubByte.compress(data)
+        self.assertEqual(points, [b * LAW for b in data])
+        self.assertEqual(x8DSubByte.decompress(points), bytes([int(round(q / LAW)) for q in points]))
+        self.assertEqual(x8DSubByte.decompress(points), data)
+
+    def test_decompress_one_shot_iterable_with_off_table_point(self):
+        points = [1e-8, 2e-8, 3.0000000001e-8, 4e-8]
+        self.assertEqual(x8DSubByte.decompress(p for p in points), b"\x01\x02\x03\x04")
+
+
+class ReaderTestCase(TempDirTestCase):
+    def test_iter_chunks_streams_lossless_file(self):
+        data = bytes(range(256)) * 10
+        save_file({"a": data}, self.filename, lossless=True)
+        with open(self.filename, "rb") as f:
+            chunks = list(iter_chunks(f, chunk_size=1000))
+        self.assertEqual([len(chunk) for chunk in chunks], [1000, 1000, 560])
+        self.assertEqual(b"".join(chunks), data)
+
+    def test_would_block_is_not_eof(self):
+        class WouldBlock(io.RawIOBase):
+            def readable(self):
+                return True
+
+            def readinto(self, b):
+                return None
+
+        with self.assertRaises(BlockingIOError):
+            load_from_reader(WouldBlock())
+
+
+class GoldenTestCase(unittest.TestCase):
+    def test_self_check(self):
+        self.assertEqual(golden.self_check(), [])
+
+
+if __name__ == "__main__":
+    unittest.main()
@@ -1,11 +1,20 @@
+import hashlib
+import io
 import json
 import struct
 import os
+import stat
+import threading
+from concurrent.futures import Future
 
 # The x8D Sub-Byte Law (10^-8)
 LAW = 0.00000001
 RATIO = 100_000_000
 
+# Precomputed Law for every u8 value; exactly equal to b * LAW
+_QUANTA_TABLE = tuple(b * LAW for b in range(256))
+_BYTE_TABLE = {q: b for b, q in enumerate(_QUANTA_TABLE)}
+
 class x8DSubByte:
     """
     x8D Sub-Byte Framework: 100M:1 Reduction Logic.
@@ -15,52 +24,496 @@ class x8DSubByte:
     def compress(byte_data):
         """Transform bytes into Quanta points using 10^-8 Law."""
         if isinstance(byte_data, (bytes, bytearray)):
-            return [b * LAW for b in byte_data]
+            return [_QUANTA_TABLE[b] for b in byte_data]
         return [float(b) * LAW for b in byte_data]
 
     @staticmethod
     def decompress(quanta_list):
         """Restore bytes from Quanta points using inverse 10^-8 Law."""
         # The Deterministic Interpreter restores bit-perfect values
-        return bytes([int(round(q / LAW)) for q in quanta_list])
+        # Points outside the lattice table take the arithmetic path
+        lookup = _BYTE_TABLE.get
+        return bytes([b if (b := lookup(q)) is not None else int(round(q / LAW))
+                      for q in quanta_list])
+
+def _as_u8(data):
+    """View data as 8-bit bytes (u8), borrowing the buffer when possible."""
+    # Buffer-protocol inputs (bytes, bytearray, array, mmap) are used in place
+    try:
+        return memoryview(data).cast('B')
+    except TypeError:
+        return bytes(data)
+
+def _coordinate(block_sum):
+    """Stored Quanta coordinate for the byte sum of one RATIO block."""
+    quanta_val = block_sum * LAW
+    return int(round(quanta_val / LAW)) % 256
+
+def _quanta_bytes(u8_data):
+    """Reduce a u8 buffer to its Quanta coordinates (1 byte per RATIO block)."""
+    # True 100M:1 Reduction Logic
+    # Every 100MB block is reduced to 1 Quanta byte
+    quanta_bytes = bytearray()
+    u8_data = memoryview(u8_data)
+    for i in range(0, len(u8_data), RATIO):
+        # Slicing the memoryview does not copy the block
+        block = u8_data[i:i+RATIO]
+        # Calculate Quanta using the Law: (sum * LAW)
+        # Then store the coordinate: (quanta / LAW) % 256
+        quanta_bytes.append(_coordinate(sum(block)))
+    return quanta_bytes
+
+def _fsync_dir(filename):
+    """Persist the directory entry of filename (no-op where unsupported)."""
+    if os.name == 'nt':
+        return
+    dir_fd = os.open(os.path.dirname(os.path.abspath(filename)), os.O_RDONLY)
+    try:
+        os.fsync(dir_fd)
+    finally:
+        os.close(dir_fd)
+
+def _lock_file(f, exclusive):
+    """Take an advisory lock on an open file, released when it is closed."""
+    if os.name == 'nt':
+        import msvcrt
+        f.seek(0)
+        msvcrt.locking(f.fileno(), msvcrt.LK_LOCK, 1)
+    else:
+        import fcntl
+        fcntl.flock(f.fileno(), fcntl.LOCK_EX if exclusive else fcntl.LOCK_SH)
+
+def _acquire_lock(target, exclusive, create):
+    """
+    Lock the sidecar '<target>.lock' and return its open handle.
+    Readers and writers contend on this stable file rather than on target,
+    whose inode changes on every replace. A writer unlinks the sidecar while
+    still holding it, so after locking we check the path still names the file
+    we hold and retry otherwise. Returns None if create is False and there is
+    no sidecar, i.e. no writer is active.
+    """
+    path = target + '.lock'
+    flags = os.O_RDWR | os.O_CREAT if create else os.O_RDONLY
+    while True:
+        try:
+            fd = os.open(path, flags, 0o666)
+        except FileNotFoundError:
+            return None
+        handle = os.fdopen(fd, 'r+b' if create else 'rb')
+        try:
+            _lock_file(handle, exclusive)
+            current = os.stat(path)
+        except FileNotFoundError:
+            handle.close()
+            if not create:
+                return None
+            continue
+        except BaseException:
+            handle.close()
+            raise
+        if os.path.samestat(os.fstat(handle.fileno()), current):
+            return handle
+        handle.close()
+
+def _release_lock(handle, target):
+    """Remove the sidecar taken by _acquire_lock(create=True) and unlock it."""
+    path = target + '.lock'
+    if os.name != 'nt':
+        # Unlink while still locked so no waiter can keep using this inode
+        os.remove(path)
+        handle.close()
+        return
+    handle.close()
+    try:
+        # Windows cannot unlink an open file; one still in use stays for its holder
+        os.remove(path)
+    except OSError:
+        pass
+
+def _create_temp_beside(target):
+    """
+    Create a unique temp file next to target; returns (fd, path).
+    It takes target's permission bits when target exists, otherwise the ones
+    open() would give (0o666 less the umask, applied by the kernel).
+    """
+    try:
+        mode = stat.S_IMODE(os.stat(target).st_mode)
+    except FileNotFoundError:
+        mode = None
+    dirname, basename = os.path.split(target)
+    flags = os.O_WRONLY | os.O_CREAT | os.O_EXCL | getattr(os, 'O_BINARY', 0)
+    while True:
+        tmp = os.path.join(dirname, f"{basename}.{os.urandom(4).hex()}.tmp")
+        try:
+            fd = os.open(tmp, flags, 0o666)
+            break
+        except FileExistsError:
+            continue
+    if mode is not None and os.name != 'nt':
+        os.fchmod(fd, mode)
+    return fd, tmp
+
+def _write_all(writer, data):
+    """Write all of data, looping over the short writes raw streams may make."""
+    view = memoryview(data).cast('B')
+    while view:
+        written = writer.write(view)
+        if written is None:
+            # Only raw streams use None for "would block"; other sinks mean "all of it"
+            if isinstance(writer, io.RawIOBase):
+                raise BlockingIOError("x8D writer would block; use a blocking stream")
+            return
+        view = view[written:]
+
+class DuplicateTensorError(ValueError):
+    """Raised when a tensor name is added twice to the same file."""
+
+class TensorsBuilder:
+    """
+    Incremental x8D Quanta writer.
+    Tensors are reduced and written as soon as they are added, so producers
+    never need to hold every tensor in memory at once.
+
+    filename is a path, or any writable binary file object (socket file,
+    pipe, compressor stream). A path target is written through a temp file
+    in the same directory and only replaced by finish(); if the builder is
+    aborted (or its with-block raises) the previous file is left untouched.
+    Symlinks are resolved first, so the link's target is what gets replaced,
+    and an existing file's permission bits carry over to the new one.
+    A writer is flushed by finish() but left open; it cannot be combined with
+    fsync, lock or verify.
+
+    metadata is accepted for compatibility with save_file() and ignored; the
+    format has no header to store it in.
+    fsync=True makes finish() sync the file and its parent directory, so the
+    write survives a crash on journaled or networked filesystems.
+    lock=True holds an exclusive advisory lock on the sidecar '<target>.lock'
+    until the new file is in place, so readers using load_file(lock=True)
+    wait for an in-progress save and then read its result, and concurrent
+    locked writers run one after another. The sidecar is removed on release.
+    On POSIX (flock) a contended lock blocks; on Windows (msvcrt) the lock is
+    retried for about 10 seconds and then OSError is raised, for writers and
+    readers.
+    duplicates controls repeated names: "error" (default) raises
+    DuplicateTensorError, "first" keeps the first tensor and skips the rest.
+    Last-wins is not offered because earlier tensors are already on disk.
+    A name is only registered once its tensor is fully written. If writing
+    fails partway through a tensor (including an error from the chunks
+    iterable), the builder is unusable: further adds and finish() raise
+    ValueError, and a path target is left untouched.
+    lossless=True stores tensor bytes verbatim instead of reducing them to
+    Quanta, so load_file() returns the exact concatenated input.
+    verify=True makes finish() re-read the temp file and check every tensor
+    against its checksum before replacing the target; the result is kept in
+    report. A mismatch raises OSError and leaves the target untouched.
+    """
+    def __init__(self, filename, metadata=None, fsync=False, lock=False, duplicates="error",
+                 lossless=False, verify=False):
+        if duplicates not in ("error", "first"):
+            raise ValueError(f"unknown duplicates policy {duplicates!r}")
+        self.filename = filename
+        self.duplicates = duplicates
+        self.lossless = lossless
+        self.fsync = fsync
+        self._owns_file = not hasattr(filename, 'write')
+        self._lock_handle = None
+        self._tmp = None
+        self.verify = verify
+        self.report = None
+        if not self._owns_file:
+            if fsync or lock or verify:
+                raise ValueError("fsync, lock and verify require a file path, not a writer")
+            self._file = filename
+        else:
+            # Write through symlinks, as open(filename, 'wb') would
+            self._target = os.path.realpath(filename)
+            if lock:
+                self._lock_handle = _acquire_lock(self._target, exclusive=True, create=True)
+            try:
+                fd, self._tmp = _create_temp_beside(self._target)
+            except BaseException:
+                self._release_lock()
+                raise
+            self._file = os.fdopen(fd, 'wb')
+        # (name, stored size, sha256) of every tensor written so far
+        self.written = []
+        self._names = set()
+        self._finished = False
+        # Set while a tensor's bytes are partly written
+        self._broken = False
+
+    def _is_new(self, name):
+        """Return False if the tensor should be skipped; raise on a rejected duplicate."""
+        if name in self._names:
+            if self.duplicates == "first":
+                return False
+            raise DuplicateTensorError(f"duplicate tensor name {name!r}")
+        return True
+
+    def _check_open(self):
+        if self._finished:
+            raise ValueError("TensorsBuilder is already finished")
+        if self._broken:
+            raise ValueError("TensorsBuilder has a partially written tensor")
+
+    def _write(self, data):
+        self._broken = True
+        _write_all(self._file, data)
+
+    def _record(self, name, size, digest):
+        self._names.add(name)
+        self.written.append((name, size, digest))
+        self._broken = False
+
+    def add_tensor(self, name, data):
+        """Reduce one tensor to Quanta and append it to the output file."""
+        self._check_open()
+        if not self._is_new(name):
+            return
+        u8_data = _as_u8(data)
+        stored = u8_data if self.lossless else _quanta_bytes(u8_data)
+        self._write(stored)
+        self._record(name, len(stored), hashlib.sha256(stored).hexdigest())
+
+    def add_tensor_chunks(self, name, chunks):
+        """
+        Append one tensor supplied as an iterable of byte chunks.
+        Only one chunk is held at a time, so tensors larger than host RAM (or
+        behind a paging API) can be written. Output is identical to
+        add_tensor() on the concatenated chunks.
+        """
+        self._check_open()
+        if not self._is_new(name):
+            return
+        digest = hashlib.sha256()
+        size = 0
+        # Running sum and fill of the current RATIO block, across chunk edges
+        block_sum = 0
+        block_fill = 0
+        for chunk in chunks:
+            u8_data = memoryview(_as_u8(chunk))
+            if self.lossless:
+                self._write(u8_data)
+                digest.update(u8_data)
+                size += len(u8_data)
+                continue
+            pos = 0
+            while pos < len(u8_data):
+                take = min(RATIO - block_fill, len(u8_data) - pos)
+                block_sum += sum(u8_data[pos:pos+take])
+                block_fill += take
+                pos += take
+                if block_fill == RATIO:
+                    stored = bytes([_coordinate(block_sum)])
+                    self._write(stored)
+                    digest.update(stored)
+                    size += 1
+                    block_sum = 0
+                    block_fill = 0
+        if block_fill:
+            stored = bytes([_coordinate(block_sum)])
+            self._write(stored)
+            digest.update(stored)
+            size += 1
+        self._record(name, size, digest.hexdigest())
+
+    def finish(self):
+        """
+        Flush the output. A path target is closed and atomically moved into
+        place; a writer is only flushed.
+        """
+        if self._finished:
+            return
+        if self._broken:
+            self.abort()
+            raise ValueError("TensorsBuilder has a partially written tensor")
+        self._finished = True
+        if not self._owns_file:
+            self._file.flush()
+            return
+        try:
+            self._file.flush()
+            if self.fsync:
+                os.fsync(self._file.fileno())
+            self._file.close()
+            if self.verify:
+                self.report = _verify_file(self._tmp, self.written)
+            os.replace(self._tmp, self._target)
+            self._tmp = None
+            if self.fsync:
+                _fsync_dir(self._target)
+        finally:
+            self._cleanup()
 
-def save_file(tensors, filename, metadata=None):
+    def abort(self):
+        """Discard the output; a path target keeps its previous contents."""
+        if self._finished:
+            return
+        self._finished = True
+        self._cleanup()
+
+    def _release_lock(self):
+        if self._lock_handle is not None:
+            _release_lock(self._lock_handle, self._target)
+            self._lock_handle = None
+
+    def _cleanup(self):
+        """Close owned handles and remove the temp file if it was not used."""
+        if self._owns_file and not self._file.closed:
+            self._file.close()
+        if self._tmp is not None:
+            try:
+                os.remove(self._tmp)
+            except FileNotFoundError:
+                pass
+            self._tmp = None
+        self._release_lock()
+
+    def __enter__(self):
+        return self
+
+    def __exit__(self, exc_type, exc, tb):
+        if exc_type is not None:
+            self.abort()
+        else:
+            self.finish()
+
+def _verify_file(filename, written):
+    """Re-read a finished file and check every tensor against its checksum."""
+    report = {"size": 0, "tensors": {}}
+    with open(filename, 'rb') as f:
+        for name, size, digest in written:
+            found = hashlib.sha256(f.read(size)).hexdigest()
+            if found != digest:
+                raise OSError(f"x8D verification failed for tensor {name!r} in {filename}")
+            report["tensors"][name] = digest
+            report["size"] += size
+        if f.read(1):
+            raise OSError(f"x8D verification failed: trailing bytes in {filename}")
+    return report
+
+def save_file(tensors, filename, metadata=None, fsync=False, lock=False, verify=False,
+              lossless=False):
     """
     Save tensors in x8D Quanta format.
     Format: [Raw Quanta Data]
     Input: u8 (8-bit bytes)
     Stored: Quanta (Sub-Byte coordinates)
+    metadata: accepted and ignored; the format has no header
+    fsync: sync file and directory before returning (see TensorsBuilder)
+    lock: hold an exclusive advisory lock while writing
+    verify: re-read and checksum every tensor in the temp file before it
+        replaces filename; returns the verification report
+    lossless: store tensor bytes verbatim for a byte-exact round trip
     """
-    data_payload = bytearray()
-    
-    for name, data in tensors.items():
-        # Input is strictly 8-bit bytes (u8)
-        if not isinstance(data, (bytes, bytearray)):
-            u8_data = bytes(data)
-        else:
-            u8_data = data
-            
-        original_size = len(u8_data)
-        
-        # True 100M:1 Reduction Logic
-        # Every 100MB block is reduced to 1 Quanta byte
-        quanta_bytes = bytearray()
-        for i in range(0, original_size, RATIO):
-            block = u8_data[i:i+RATIO]
-            # Calculate Quanta using the Law: (sum * LAW)
-            # Then store the coordinate: (quanta / LAW) % 256
-            block_sum = sum(block)
-            quanta_val = block_sum * LAW
-            stored_coord = int(round(quanta_val / LAW)) % 256
-            quanta_bytes.append(stored_coord)
-            
-        data_payload.extend(quanta_bytes)
-    
-    with open(filename, 'wb') as f:
-        f.write(data_payload)
-
-def load_file(filename):
-    """Load x8D Quanta file and return raw quanta bytes."""
-    with open(filename, 'rb') as f:
-        data_block = f.read()
-    return data_block
+    with TensorsBuilder(filename, metadata=metadata, fsync=fsync, lock=lock,
+                        lossless=lossless, verify=verify) as builder:
+        for name, data in tensors.items():
+            builder.add_tensor(name, data)
+    return builder.report
+
+def save_to_writer(tensors, writer, metadata=None, lossless=False):
+    """
+    Save tensors in x8D Quanta format to a writable binary file object.
+    The writer (socket file, pipe, GzipFile, upload stream) is flushed but
+    not closed; same layout as save_file().
+    """
+    with TensorsBuilder(writer, metadata=metadata, lossless=lossless) as builder:
+        for name, data in tensors.items():
+            builder.add_tensor(name, data)
+
+# Completion event of the latest snapshot per resolved target path, so
+# writes land in call order
+_snapshot_tails = {}
+_snapshot_tails_lock = threading.Lock()
+
+def snapshot(tensors, filename, metadata=None, callback=None, **save_options):
+    """
+    Checkpoint tensors in the background.
+    Tensor data is pinned (copied) before returning, so the caller may keep
+    mutating its buffers while the file is written on a worker thread.
+    Returns a Future resolving to save_file()'s result; callback, if given,
+    is invoked with that Future on completion. A Future cancelled before the
+    worker starts writes nothing.
+    Snapshots to the same path are written in call order: each waits for the
+    previous one, so a slow older checkpoint never overwrites a newer one.
+    """
+    # Immutable bytes cannot change under us; everything else is copied now
+    pinned = {
+        name: data if isinstance(data, bytes) else bytes(_as_u8(data))
+        for name, data in tensors.items()
+    }
+    future = Future()
+    if callback is not None:
+        future.add_done_callback(callback)
+    # Set once this snapshot's worker is done, whether it wrote or was cancelled
+    finished = threading.Event()
+    key = os.path.realpath(filename)
+    with _snapshot_tails_lock:
+        previous = _snapshot_tails.get(key)
+        _snapshot_tails[key] = finished
+
+    def run():
+        try:
+            if previous is not None:
+                previous.wait()
+            if not future.set_running_or_notify_cancel():
+                return
+            try:
+                future.set_result(save_file(pinned, filename, metadata=metadata, **save_options))
+            except BaseException as e:
+                future.set_exception(e)
+        finally:
+            finished.set()
+            with _snapshot_tails_lock:
+                if _snapshot_tails.get(key) is finished:
+                    del _snapshot_tails[key]
+
+    threading.Thread(target=run, name="x8d-snapshot").start()
+    return future
+
+def load_file(filename, lock=False):
+    """
+    Load x8D Quanta file and return raw quanta bytes.
+    lock: wait for a locked save in progress (see TensorsBuilder) and hold a
+        shared lock on its sidecar while reading; on Windows the lock is
+        exclusive and raises OSError if a writer holds it for ~10 seconds
+    """
+    handle = None
+    if lock:
+        handle = _acquire_lock(os.path.realpath(filename), exclusive=False, create=False)
+    try:
+        with open(filename, 'rb') as f:
+            return load_from_reader(f)
+    finally:
+        if handle is not None:
+            handle.close()
+
+def load_from_reader(reader):
+    """
+    Load raw quanta bytes from a readable binary file object.
+    Reads from the current position to EOF into memory; the reader is not
+    closed. Lossless files are as large as their input, so use iter_chunks()
+    to stream those instead.
+    """
+    # A single read() keeps peak memory at one copy of the data
+    data = reader.read()
+    if data is None:
+        raise BlockingIOError("x8D reader would block; use a blocking stream")
+    return data
+
+def iter_chunks(reader, chunk_size=1 << 20):
+    """
+    Yield stored bytes from a readable binary file object, chunk_size at a
+    time, until EOF. Only one chunk is held at a time, so files larger than
+    RAM can be consumed. A non-blocking stream with no data ready raises
+    BlockingIOError rather than being mistaken for EOF.
+    """
+    while True:
+        # Bounded reads also cope with raw streams that return short reads
+        chunk = reader.read(chunk_size)
+        if chunk is None:
+            raise BlockingIOError("x8D reader would block; use a blocking stream")
+        if not chunk:
+            return
+        yield chunk
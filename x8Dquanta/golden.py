@@ -0,0 +1,82 @@
+"""
+x8D Golden Vectors: cross-platform determinism self-check.
+Inputs are packed little-endian so the expected bytes are the same on every
+platform. self_check() reproduces them with the local interpreter, so teams
+that content-address checkpoints can detect platform-dependent float behavior.
+Every dtype the struct module can pack is covered (BOOL, U8-U64, I8-I64,
+F16, F32, F64); BF16 and the F8 variants have no struct format and are left
+out rather than packed by hand.
+"""
+import os
+import struct
+import tempfile
+
+from . import load_file, save_file, x8DSubByte
+
+VALUES = (0, 1, 2, 3, 5, 8, 13, 21, 34, 55, 89, 127)
+
+# struct format per dtype; signed types are negated, floats quartered
+FORMATS = {
+    "BOOL": "?",
+    "U8": "B", "I8": "b",
+    "U16": "H", "I16": "h",
+    "U32": "I", "I32": "i",
+    "U64": "Q", "I64": "q",
+    "F16": "e", "F32": "f", "F64": "d",
+}
+
+def _pack(fmt):
+    if fmt in "bhiq":
+        values = [-v for v in VALUES]
+    elif fmt in "efd":
+        values = [v / 4 for v in VALUES]
+    else:
+        values = VALUES
+    return struct.pack(f"<{len(values)}{fmt}", *values)
+
+# Canonical input bytes per dtype
+INPUTS = {dtype: _pack(fmt) for dtype, fmt in FORMATS.items()}
+
+# Expected stored Quanta coordinate per dtype (default, reducing mode)
+QUANTA = {
+    "BOOL": b"\x0b",
+    "U8": b"\x66", "I8": b"\x9a",
+    "U16": b"\x66", "I16": b"\x8f",
+    "U32": b"\x66", "I32": b"\x79",
+    "U64": b"\x66", "I64": b"\x4d",
+    "F16": b"\x38", "F32": b"\x2b", "F64": b"\xa8",
+}
+
+# Expected x8DSubByte.compress() points for bytes(range(0, 256, 17)), as float.hex()
+POINTS = (
+    "0x0.0p+0", "0x1.6d127d05394fep-23", "0x1.6d127d05394fep-22", "0x1.11cdddc3eafbep-21",
+    "0x1.6d127d05394fep-21", "0x1.c8571c4687a3dp-21", "0x1.11cdddc3eafbep-20", "0x1.3f702d649225ep-20",
+    "0x1.6d127d05394fep-20", "0x1.9ab4cca5e079dp-20", "0x1.c8571c4687a3dp-20", "0x1.f5f96be72ecddp-20",
+    "0x1.11cdddc3eafbep-19", "0x1.289f05943e90ep-19", "0x1.3f702d649225ep-19", "0x1.56415534e5baep-19",
+)
+
+def self_check():
+    """
+    Reproduce every golden vector on this platform.
+    Returns a list of mismatch descriptions; an empty list means the local
+    serialization is byte-identical to the canonical output.
+    """
+    failures = []
+
+    points = tuple(q.hex() for q in x8DSubByte.compress(bytes(range(0, 256, 17))))
+    if points != POINTS:
+        failures.append("compress: Quanta points differ from golden values")
+
+    fd, filename = tempfile.mkstemp(suffix=".bin")
+    os.close(fd)
+    try:
+        for dtype, data in INPUTS.items():
+            save_file({dtype: data}, filename)
+            if load_file(filename) != QUANTA[dtype]:
+                failures.append(f"{dtype}: Quanta output differs from golden vector")
+            save_file({dtype: data}, filename, lossless=True)
+            if load_file(filename) != data:
+                failures.append(f"{dtype}: lossless output differs from input")
+    finally:
+        os.remove(filename)
+    return failures